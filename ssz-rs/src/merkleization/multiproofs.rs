@@ -1,4 +1,10 @@
 //! Experimental support for multiproofs.
+//!
+//! `calculate_merkle_root`, `calculate_multi_merkle_root`,
+//! `generate_multiproof` and `CachedTree` default to `Sha256`, but also come
+//! in a `_with_hasher` form generic over any `digest::Digest` with a 32-byte
+//! output, since `Node` is still a fixed 32-byte array. The `verify_*` and
+//! range-proof functions always use `Sha256`.
 use crate::{
     lib::*,
     merkleization::{
@@ -6,7 +12,9 @@ use crate::{
         GeneralizedIndex, MerkleizationError as Error, Node,
     },
 };
-use sha2::{Digest, Sha256};
+use core::marker::PhantomData;
+use digest::{Digest, FixedOutputReset};
+use sha2::Sha256;
 
 fn get_branch_indices(tree_index: GeneralizedIndex) -> Vec<GeneralizedIndex> {
     let mut focus = sibling(tree_index);
@@ -50,31 +58,44 @@ fn get_helper_indices(indices: &[GeneralizedIndex]) -> Vec<GeneralizedIndex> {
     all_branch_indices
 }
 
-pub fn calculate_merkle_root(
+/// Generic counterpart of [`calculate_merkle_root`]; rejects any `D` whose
+/// output isn't 32 bytes, since `Node` is a fixed-size array.
+pub fn calculate_merkle_root_with_hasher<D: Digest + FixedOutputReset>(
     leaf: Node,
     proof: &[Node],
     index: GeneralizedIndex,
 ) -> Result<Node, Error> {
+    if <D as Digest>::output_size() != 32 {
+        return Err(Error::InvalidProof);
+    }
     let path_length = get_path_length(index)?;
     if path_length != proof.len() {
         return Err(Error::InvalidProof);
     }
     let mut result = leaf;
 
-    let mut hasher = Sha256::new();
+    let mut hasher = D::new();
     for (i, next) in proof.iter().enumerate() {
         if get_bit(index, i) {
-            hasher.update(next);
-            hasher.update(result);
+            Digest::update(&mut hasher, next);
+            Digest::update(&mut hasher, result);
         } else {
-            hasher.update(result);
-            hasher.update(next);
+            Digest::update(&mut hasher, result);
+            Digest::update(&mut hasher, next);
         }
         result.copy_from_slice(&hasher.finalize_reset());
     }
     Ok(result)
 }
 
+pub fn calculate_merkle_root(
+    leaf: Node,
+    proof: &[Node],
+    index: GeneralizedIndex,
+) -> Result<Node, Error> {
+    calculate_merkle_root_with_hasher::<Sha256>(leaf, proof, index)
+}
+
 pub fn verify_merkle_proof(
     leaf: Node,
     proof: &[Node],
@@ -88,11 +109,16 @@ pub fn verify_merkle_proof(
     }
 }
 
-pub fn calculate_multi_merkle_root(
+/// Generic counterpart of [`calculate_multi_merkle_root`]; see
+/// [`calculate_merkle_root_with_hasher`].
+pub fn calculate_multi_merkle_root_with_hasher<D: Digest + FixedOutputReset>(
     leaves: &[Node],
     proof: &[Node],
     indices: &[GeneralizedIndex],
 ) -> Result<Node, Error> {
+    if <D as Digest>::output_size() != 32 {
+        return Err(Error::InvalidProof);
+    }
     // Validate input
     if leaves.len() != indices.len() {
         return Err(Error::InvalidProof);
@@ -116,7 +142,7 @@ pub fn calculate_multi_merkle_root(
     let mut keys = objects.keys().cloned().collect::<Vec<_>>();
     keys.sort_by(|a, b| b.cmp(a));
 
-    let mut hasher = Sha256::new();
+    let mut hasher = D::new();
     let mut pos = 0;
     while pos < keys.len() {
         let key = keys.get(pos).unwrap();
@@ -134,8 +160,8 @@ pub fn calculate_multi_merkle_root(
             let left_index = sibling(right_index);
             let left_input = objects.get(&left_index).expect("contains index");
             let right_input = objects.get(&right_index).expect("contains index");
-            hasher.update(left_input);
-            hasher.update(right_input);
+            Digest::update(&mut hasher, left_input);
+            Digest::update(&mut hasher, right_input);
 
             let parent = objects.entry(parent_index).or_default();
             parent.copy_from_slice(&hasher.finalize_reset());
@@ -148,6 +174,14 @@ pub fn calculate_multi_merkle_root(
     Ok(root)
 }
 
+pub fn calculate_multi_merkle_root(
+    leaves: &[Node],
+    proof: &[Node],
+    indices: &[GeneralizedIndex],
+) -> Result<Node, Error> {
+    calculate_multi_merkle_root_with_hasher::<Sha256>(leaves, proof, indices)
+}
+
 pub fn verify_merkle_multiproof(
     leaves: &[Node],
     proof: &[Node],
@@ -161,6 +195,408 @@ pub fn verify_merkle_multiproof(
     }
 }
 
+/// Builds the full merkle tree backing `leaves` and selects the helper nodes
+/// needed to prove `indices`, returned in the same descending-generalized-index
+/// order `calculate_multi_merkle_root` expects. `leaves` must be the complete,
+/// power-of-two-padded bottom layer of the tree.
+pub fn generate_multiproof(
+    leaves: &[Node],
+    indices: &[GeneralizedIndex],
+) -> Result<(Vec<Node>, Vec<GeneralizedIndex>), Error> {
+    generate_multiproof_with_hasher::<Sha256>(leaves, indices)
+}
+
+/// Generic counterpart of [`generate_multiproof`]; see
+/// [`calculate_merkle_root_with_hasher`].
+pub fn generate_multiproof_with_hasher<D: Digest + FixedOutputReset>(
+    leaves: &[Node],
+    indices: &[GeneralizedIndex],
+) -> Result<(Vec<Node>, Vec<GeneralizedIndex>), Error> {
+    if <D as Digest>::output_size() != 32 {
+        return Err(Error::InvalidProof);
+    }
+    if leaves.is_empty() || !leaves.len().is_power_of_two() {
+        return Err(Error::InvalidProof);
+    }
+
+    let leaf_count = leaves.len();
+    for index in indices {
+        if *index < leaf_count || *index >= 2 * leaf_count {
+            return Err(Error::InvalidProof);
+        }
+    }
+
+    // Build the full tree bottom-up, keyed by generalized index.
+    let mut tree = vec![Node::default(); 2 * leaf_count];
+    tree[leaf_count..].copy_from_slice(leaves);
+
+    let mut hasher = D::new();
+    for i in (1..leaf_count).rev() {
+        Digest::update(&mut hasher, tree[2 * i]);
+        Digest::update(&mut hasher, tree[2 * i + 1]);
+        tree[i].copy_from_slice(&hasher.finalize_reset());
+    }
+
+    let helper_indices = get_helper_indices(indices);
+    let proof = helper_indices.iter().map(|index| tree[*index]).collect();
+
+    Ok((proof, helper_indices))
+}
+
+/// Generates a multiproof for the contiguous leaf range `[start, end)` of the
+/// full, power-of-two-padded chunk layer `leaves` of a `List`/`Vector`. Pass
+/// the result to [`verify_range_proof`] along with the type's `length`
+/// mix-in node, if any.
+pub fn generate_range_proof(
+    leaves: &[Node],
+    start: usize,
+    end: usize,
+) -> Result<(Vec<Node>, Vec<Node>), Error> {
+    if start >= end || end > leaves.len() {
+        return Err(Error::InvalidProof);
+    }
+
+    let chunk_count = leaves.len();
+    let range_indices = (start..end).map(|i| chunk_count + i).collect::<Vec<_>>();
+    let (proof, _) = generate_multiproof(leaves, &range_indices)?;
+    Ok((leaves[start..end].to_vec(), proof))
+}
+
+/// Verifies that `leaves` are exactly the contiguous range `[start, end)` of a
+/// `List`/`Vector` with `chunk_count` total (power-of-two-padded) chunks,
+/// committed to under `root`. `length` is the SSZ length mix-in leaf for
+/// `List`s (`None` for `Vector`s); the wrong length node, or a missing one for
+/// a list, makes this reject, so an append/truncation outside the range can't
+/// forge membership.
+pub fn verify_range_proof(
+    root: Node,
+    start: usize,
+    end: usize,
+    leaves: &[Node],
+    proof: &[Node],
+    chunk_count: usize,
+    length: Option<Node>,
+) -> Result<(), Error> {
+    if !chunk_count.is_power_of_two() ||
+        start >= end ||
+        end > chunk_count ||
+        leaves.len() != end - start
+    {
+        return Err(Error::InvalidProof);
+    }
+
+    let range_indices = (start..end).map(|i| chunk_count + i).collect::<Vec<_>>();
+    let chunks_root = calculate_multi_merkle_root(leaves, proof, &range_indices)?;
+
+    let claimed_root = match length {
+        Some(length_node) => {
+            let mut hasher = Sha256::new();
+            hasher.update(chunks_root);
+            hasher.update(length_node);
+            let mut combined = Node::default();
+            combined.copy_from_slice(&hasher.finalize());
+            combined
+        }
+        None => chunks_root,
+    };
+
+    if claimed_root == root {
+        Ok(())
+    } else {
+        Err(Error::InvalidProof)
+    }
+}
+
+/// Controls the on-wire ordering of helper nodes within a serialized
+/// [`Multiproof`], modeled on the "direct hashes order" vs. "reverse hashes
+/// order" distinction used by other merkle proof formats.
+pub trait MultiproofSerializer {
+    /// Reorders a set of helper indices into the order their matching proof
+    /// nodes should be written in.
+    fn order(helper_indices: &[GeneralizedIndex]) -> Vec<GeneralizedIndex>;
+}
+
+/// Writes helper nodes in ascending-generalized-index order.
+pub struct DirectHashesOrder;
+
+impl MultiproofSerializer for DirectHashesOrder {
+    fn order(helper_indices: &[GeneralizedIndex]) -> Vec<GeneralizedIndex> {
+        let mut ordered = helper_indices.to_vec();
+        ordered.sort();
+        ordered
+    }
+}
+
+/// Writes helper nodes in descending-generalized-index order, i.e. the order
+/// `get_helper_indices` and `calculate_multi_merkle_root` already use
+/// internally.
+pub struct ReverseHashesOrder;
+
+impl MultiproofSerializer for ReverseHashesOrder {
+    fn order(helper_indices: &[GeneralizedIndex]) -> Vec<GeneralizedIndex> {
+        let mut ordered = helper_indices.to_vec();
+        ordered.sort_by(|a, b| b.cmp(a));
+        ordered
+    }
+}
+
+/// A self-contained multiproof: the leaves being proven, the generalized
+/// indices they sit at, the helper nodes required to recompute the root, and
+/// the root the proof is claimed against.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Multiproof {
+    pub root: Node,
+    pub indices: Vec<GeneralizedIndex>,
+    pub leaves: Vec<Node>,
+    pub proof: Vec<Node>,
+}
+
+impl Multiproof {
+    pub fn new(
+        root: Node,
+        indices: Vec<GeneralizedIndex>,
+        leaves: Vec<Node>,
+        proof: Vec<Node>,
+    ) -> Self {
+        Self { root, indices, leaves, proof }
+    }
+
+    /// Builds a multiproof for `indices` out of the full, power-of-two-padded
+    /// leaf layer `leaves`, deriving the claimed root from those same leaves.
+    pub fn from_leaves(leaves: &[Node], indices: &[GeneralizedIndex]) -> Result<Self, Error> {
+        let (proof, _) = generate_multiproof(leaves, indices)?;
+        let selected_leaves =
+            indices.iter().map(|index| leaves[*index - leaves.len()]).collect::<Vec<_>>();
+        let root = calculate_multi_merkle_root(&selected_leaves, &proof, indices)?;
+        Ok(Self { root, indices: indices.to_vec(), leaves: selected_leaves, proof })
+    }
+
+    pub fn verify(&self) -> Result<(), Error> {
+        verify_merkle_multiproof(&self.leaves, &self.proof, &self.indices, self.root)
+    }
+
+    /// Serializes this multiproof to bytes, writing helper nodes in the order
+    /// `S` selects.
+    ///
+    /// Layout: `[index_count: u32][proof_count: u32][root: 32 bytes]`,
+    /// followed by `index_count` generalized indices (`u64` little-endian),
+    /// `index_count` leaves (32 bytes each), then `proof_count` proof nodes
+    /// (32 bytes each).
+    pub fn serialize<S: MultiproofSerializer>(&self) -> Vec<u8> {
+        let mut result =
+            Vec::with_capacity(8 + 32 + self.indices.len() * (8 + 32) + self.proof.len() * 32);
+        result.extend_from_slice(&(self.indices.len() as u32).to_le_bytes());
+        result.extend_from_slice(&(self.proof.len() as u32).to_le_bytes());
+        result.extend_from_slice(&self.root);
+        for index in &self.indices {
+            result.extend_from_slice(&(*index as u64).to_le_bytes());
+        }
+        for leaf in &self.leaves {
+            result.extend_from_slice(leaf);
+        }
+
+        let helper_indices = get_helper_indices(&self.indices);
+        let nodes_by_index: HashMap<_, _> =
+            helper_indices.iter().cloned().zip(self.proof.iter().cloned()).collect();
+        for index in S::order(&helper_indices) {
+            result.extend_from_slice(&nodes_by_index[&index]);
+        }
+        result
+    }
+
+    /// Deserializes a multiproof previously written by [`Multiproof::serialize`]
+    /// with the same ordering `S`, validating that the encoded proof has
+    /// exactly the number of helper nodes `indices` requires.
+    pub fn deserialize<S: MultiproofSerializer>(data: &[u8]) -> Result<Self, Error> {
+        if data.len() < 8 + 32 {
+            return Err(Error::InvalidProof);
+        }
+        let index_count = u32::from_le_bytes(data[0..4].try_into().unwrap()) as usize;
+        let proof_count = u32::from_le_bytes(data[4..8].try_into().unwrap()) as usize;
+
+        let mut offset = 8;
+        let mut root = Node::default();
+        root.copy_from_slice(&data[offset..offset + 32]);
+        offset += 32;
+
+        // `index_count`/`proof_count` come straight off the wire, so every
+        // multiplication and offset built from them must be checked: on a
+        // 32-bit `usize` target a large enough count overflows and wraps,
+        // which would let this length check pass incorrectly and the
+        // indexing below run out of bounds.
+        let indices_len = index_count.checked_mul(8).ok_or(Error::InvalidProof)?;
+        let leaves_len = index_count.checked_mul(32).ok_or(Error::InvalidProof)?;
+        let proof_len = proof_count.checked_mul(32).ok_or(Error::InvalidProof)?;
+        let expected_len = offset
+            .checked_add(indices_len)
+            .and_then(|len| len.checked_add(leaves_len))
+            .and_then(|len| len.checked_add(proof_len))
+            .ok_or(Error::InvalidProof)?;
+        if data.len() != expected_len {
+            return Err(Error::InvalidProof);
+        }
+
+        let mut indices = Vec::with_capacity(index_count);
+        for i in 0..index_count {
+            let start = offset + i * 8;
+            let raw = u64::from_le_bytes(data[start..start + 8].try_into().unwrap());
+            indices.push(raw as GeneralizedIndex);
+        }
+        offset += indices_len;
+
+        let mut leaves = Vec::with_capacity(index_count);
+        for i in 0..index_count {
+            let start = offset + i * 32;
+            let mut leaf = Node::default();
+            leaf.copy_from_slice(&data[start..start + 32]);
+            leaves.push(leaf);
+        }
+        offset += leaves_len;
+
+        let helper_indices = get_helper_indices(&indices);
+        if proof_count != helper_indices.len() {
+            return Err(Error::InvalidProof);
+        }
+
+        let ordered = S::order(&helper_indices);
+        let mut nodes_by_index = HashMap::new();
+        for (i, index) in ordered.iter().enumerate() {
+            let start = offset + i * 32;
+            let mut node = Node::default();
+            node.copy_from_slice(&data[start..start + 32]);
+            nodes_by_index.insert(*index, node);
+        }
+        let proof = helper_indices.iter().map(|index| nodes_by_index[index]).collect();
+
+        Ok(Self { root, indices, leaves, proof })
+    }
+}
+
+/// An incrementally-maintained merkle tree, keyed by generalized index.
+/// [`CachedTree::update_leaf`] only marks a leaf dirty;
+/// [`CachedTree::root`] then rehashes just the paths from the dirty leaves up
+/// to the root, `O(k log n)` for `k`
+/// changed leaves instead of a full `O(n)` re-hash.
+pub struct CachedTree<D: Digest = Sha256> {
+    leaf_count: usize,
+    nodes: HashMap<GeneralizedIndex, Node>,
+    dirty_leaves: HashSet<GeneralizedIndex>,
+    _hasher: PhantomData<D>,
+}
+
+impl CachedTree<Sha256> {
+    /// Builds a cache from the full, power-of-two-padded leaf layer of a
+    /// tree, hashing it once up front with the default `Sha256` hasher.
+    pub fn new(leaves: &[Node]) -> Result<Self, Error> {
+        Self::new_with_hasher(leaves)
+    }
+}
+
+impl<D: Digest + FixedOutputReset> CachedTree<D> {
+    /// Generic counterpart of [`CachedTree::new`]; see
+    /// [`calculate_merkle_root_with_hasher`].
+    pub fn new_with_hasher(leaves: &[Node]) -> Result<Self, Error> {
+        if <D as Digest>::output_size() != 32 {
+            return Err(Error::InvalidProof);
+        }
+        if leaves.is_empty() || !leaves.len().is_power_of_two() {
+            return Err(Error::InvalidProof);
+        }
+
+        let leaf_count = leaves.len();
+        let mut nodes = HashMap::new();
+        for (i, leaf) in leaves.iter().enumerate() {
+            nodes.insert(leaf_count + i, *leaf);
+        }
+
+        let mut cache =
+            Self { leaf_count, nodes, dirty_leaves: HashSet::new(), _hasher: PhantomData };
+        let all_leaf_indices = (0..leaf_count).map(|i| leaf_count + i).collect::<Vec<_>>();
+        cache.recompute_ancestors_of(&all_leaf_indices);
+        Ok(cache)
+    }
+
+    /// Records `leaf` at `index` and marks it dirty. Its ancestors are not
+    /// rehashed until the next call to [`CachedTree::root`] or
+    /// [`CachedTree::generate_multiproof`].
+    pub fn update_leaf(&mut self, index: GeneralizedIndex, leaf: Node) -> Result<(), Error> {
+        if index < self.leaf_count || index >= 2 * self.leaf_count {
+            return Err(Error::InvalidProof);
+        }
+        self.nodes.insert(index, leaf);
+        self.dirty_leaves.insert(index);
+        Ok(())
+    }
+
+    /// Recomputes and returns the root, rehashing only the ancestors of
+    /// leaves dirtied since the last call.
+    pub fn root(&mut self) -> Node {
+        if !self.dirty_leaves.is_empty() {
+            let dirty = self.dirty_leaves.drain().collect::<Vec<_>>();
+            self.recompute_ancestors_of(&dirty);
+        }
+        *self.nodes.get(&1).expect("root is always present once the cache is built")
+    }
+
+    /// Returns the helper nodes needed to prove `indices`, reading them
+    /// straight out of the cache (rehashing dirty paths first if needed)
+    /// instead of rebuilding the whole tree.
+    pub fn generate_multiproof(
+        &mut self,
+        indices: &[GeneralizedIndex],
+    ) -> Result<(Vec<Node>, Vec<GeneralizedIndex>), Error> {
+        for index in indices {
+            if *index < self.leaf_count || *index >= 2 * self.leaf_count {
+                return Err(Error::InvalidProof);
+            }
+        }
+
+        self.root();
+
+        let helper_indices = get_helper_indices(indices);
+        let mut proof = Vec::with_capacity(helper_indices.len());
+        for index in &helper_indices {
+            proof.push(*self.nodes.get(index).ok_or(Error::InvalidProof)?);
+        }
+        Ok((proof, helper_indices))
+    }
+
+    fn recompute_ancestors_of(&mut self, changed: &[GeneralizedIndex]) {
+        let mut to_recompute = HashSet::new();
+        for index in changed {
+            // A single-leaf tree's only leaf is the root itself (generalized
+            // index 1), which has no parent to climb to.
+            if *index == 1 {
+                continue;
+            }
+            let mut focus = parent(*index);
+            loop {
+                let inserted = to_recompute.insert(focus);
+                if focus == 1 || !inserted {
+                    break;
+                }
+                focus = parent(focus);
+            }
+        }
+
+        // Descending order visits children (larger generalized indices)
+        // before their parents, same as `calculate_multi_merkle_root`.
+        let mut ordered = to_recompute.into_iter().collect::<Vec<_>>();
+        ordered.sort_by(|a, b| b.cmp(a));
+
+        let mut hasher = D::new();
+        for index in ordered {
+            let left = *self.nodes.get(&(2 * index)).expect("left child already hashed");
+            let right = *self.nodes.get(&(2 * index + 1)).expect("right child already hashed");
+            Digest::update(&mut hasher, left);
+            Digest::update(&mut hasher, right);
+            let mut parent_node = Node::default();
+            parent_node.copy_from_slice(&hasher.finalize_reset());
+            self.nodes.insert(index, parent_node);
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -199,4 +635,275 @@ mod tests {
         let result = verify_merkle_multiproof(&leaves, &proof, &indices, root);
         assert!(result.is_ok());
     }
+
+    #[test]
+    fn test_generate_multiproof_round_trips_with_verify() {
+        // A tree of 4 leaves: generalized indices 4, 5, 6, 7.
+        let all_leaves = (0..4u8)
+            .map(|i| {
+                let mut node = Node::default();
+                node[0] = i;
+                node
+            })
+            .collect::<Vec<_>>();
+
+        let indices = vec![4, 6];
+        let leaves = vec![all_leaves[0], all_leaves[2]];
+
+        let (proof, helper_indices) = generate_multiproof(&all_leaves, &indices).unwrap();
+        assert_eq!(helper_indices, get_helper_indices(&indices));
+
+        let root = calculate_multi_merkle_root(&leaves, &proof, &indices).unwrap();
+
+        let result = verify_merkle_multiproof(&leaves, &proof, &indices, root);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_generate_multiproof_rejects_out_of_range_index() {
+        let all_leaves = vec![Node::default(); 4];
+        let result = generate_multiproof(&all_leaves, &[1]);
+        assert!(result.is_err());
+    }
+
+    fn four_leaves() -> Vec<Node> {
+        (0..4u8)
+            .map(|i| {
+                let mut node = Node::default();
+                node[0] = i;
+                node
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_multiproof_serialize_deserialize_round_trip() {
+        let all_leaves = four_leaves();
+        let multiproof = Multiproof::from_leaves(&all_leaves, &[4, 6]).unwrap();
+        assert!(multiproof.verify().is_ok());
+
+        let bytes = multiproof.serialize::<ReverseHashesOrder>();
+        let decoded = Multiproof::deserialize::<ReverseHashesOrder>(&bytes).unwrap();
+        assert_eq!(decoded, multiproof);
+        assert!(decoded.verify().is_ok());
+
+        let bytes = multiproof.serialize::<DirectHashesOrder>();
+        let decoded = Multiproof::deserialize::<DirectHashesOrder>(&bytes).unwrap();
+        assert_eq!(decoded, multiproof);
+        assert!(decoded.verify().is_ok());
+    }
+
+    // A minimal non-cryptographic `Digest` whose output is shorter than the
+    // 32 bytes a `Node` holds, standing in for a real non-SHA256 hasher (e.g.
+    // Poseidon) to exercise the output-size guard below.
+    #[derive(Default)]
+    struct SixteenByteHasher {
+        buf: Vec<u8>,
+    }
+
+    impl digest::HashMarker for SixteenByteHasher {}
+
+    impl digest::OutputSizeUser for SixteenByteHasher {
+        type OutputSize = digest::consts::U16;
+    }
+
+    impl digest::Update for SixteenByteHasher {
+        fn update(&mut self, data: &[u8]) {
+            self.buf.extend_from_slice(data);
+        }
+    }
+
+    impl digest::FixedOutput for SixteenByteHasher {
+        fn finalize_into(self, out: &mut digest::Output<Self>) {
+            out.fill(0);
+            let len = out.len();
+            for (i, byte) in self.buf.iter().enumerate() {
+                out[i % len] ^= *byte;
+            }
+        }
+    }
+
+    impl digest::Reset for SixteenByteHasher {
+        fn reset(&mut self) {
+            self.buf.clear();
+        }
+    }
+
+    impl FixedOutputReset for SixteenByteHasher {
+        fn finalize_into_reset(&mut self, out: &mut digest::Output<Self>) {
+            out.fill(0);
+            let len = out.len();
+            for (i, byte) in self.buf.iter().enumerate() {
+                out[i % len] ^= *byte;
+            }
+            self.buf.clear();
+        }
+    }
+
+    #[test]
+    fn test_with_hasher_rejects_non_32_byte_output() {
+        let all_leaves = four_leaves();
+
+        assert!(matches!(
+            calculate_merkle_root_with_hasher::<SixteenByteHasher>(all_leaves[0], &[], 2),
+            Err(Error::InvalidProof)
+        ));
+        assert!(matches!(
+            generate_multiproof_with_hasher::<SixteenByteHasher>(&all_leaves, &[4, 6]),
+            Err(Error::InvalidProof)
+        ));
+        assert!(matches!(
+            calculate_multi_merkle_root_with_hasher::<SixteenByteHasher>(
+                &[all_leaves[0], all_leaves[2]],
+                &[],
+                &[4, 6]
+            ),
+            Err(Error::InvalidProof)
+        ));
+        assert!(matches!(
+            CachedTree::<SixteenByteHasher>::new_with_hasher(&all_leaves),
+            Err(Error::InvalidProof)
+        ));
+    }
+
+    #[test]
+    fn test_with_hasher_matches_default_sha256() {
+        let all_leaves = four_leaves();
+        let (proof, indices) = generate_multiproof(&all_leaves, &[4, 6]).unwrap();
+        let (proof_generic, indices_generic) =
+            generate_multiproof_with_hasher::<Sha256>(&all_leaves, &[4, 6]).unwrap();
+        assert_eq!(proof, proof_generic);
+        assert_eq!(indices, indices_generic);
+
+        let leaves = vec![all_leaves[0], all_leaves[2]];
+        let root = calculate_multi_merkle_root(&leaves, &proof, &[4, 6]).unwrap();
+        let root_generic =
+            calculate_multi_merkle_root_with_hasher::<Sha256>(&leaves, &proof, &[4, 6]).unwrap();
+        assert_eq!(root, root_generic);
+    }
+
+    #[test]
+    fn test_multiproof_deserialize_rejects_wrong_proof_count() {
+        let all_leaves = four_leaves();
+        let multiproof = Multiproof::from_leaves(&all_leaves, &[4, 6]).unwrap();
+        let mut bytes = multiproof.serialize::<ReverseHashesOrder>();
+        // Lie about the proof count in the header.
+        bytes[4..8].copy_from_slice(&0u32.to_le_bytes());
+        let result = Multiproof::deserialize::<ReverseHashesOrder>(&bytes);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_multiproof_deserialize_rejects_huge_header_counts_without_panicking() {
+        // A hostile header claiming huge index/proof counts must be rejected
+        // cleanly, not panic from an overflowing `index_count * 8`-style
+        // multiplication (which would wrap on a 32-bit `usize` target).
+        let mut bytes = Vec::new();
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        bytes.extend_from_slice(&u32::MAX.to_le_bytes());
+        bytes.extend_from_slice(&[0u8; 32]);
+        let result = Multiproof::deserialize::<ReverseHashesOrder>(&bytes);
+        assert!(matches!(result, Err(Error::InvalidProof)));
+    }
+
+    #[test]
+    fn test_cached_tree_matches_full_reroot_after_update() {
+        let mut leaves = four_leaves();
+        let mut cache = CachedTree::new(&leaves).unwrap();
+        assert_eq!(cache.root(), calculate_merkle_root_from_leaves(&leaves));
+
+        leaves[2][0] = 99;
+        cache.update_leaf(6, leaves[2]).unwrap();
+        assert_eq!(cache.root(), calculate_merkle_root_from_leaves(&leaves));
+    }
+
+    #[test]
+    fn test_cached_tree_generate_multiproof_matches_stateless_version() {
+        let mut leaves = four_leaves();
+        leaves[1][0] = 42;
+        let mut cache = CachedTree::new(&leaves).unwrap();
+        cache.update_leaf(5, leaves[1]).unwrap();
+
+        let (cached_proof, cached_indices) = cache.generate_multiproof(&[4, 6]).unwrap();
+        let (proof, indices) = generate_multiproof(&leaves, &[4, 6]).unwrap();
+        assert_eq!(cached_proof, proof);
+        assert_eq!(cached_indices, indices);
+    }
+
+    #[test]
+    fn test_cached_tree_generate_multiproof_rejects_out_of_range_index() {
+        let leaves = four_leaves();
+        let mut cache = CachedTree::new(&leaves).unwrap();
+        assert!(cache.generate_multiproof(&[1]).is_err());
+    }
+
+    #[test]
+    fn test_cached_tree_single_leaf_does_not_panic() {
+        let leaf = {
+            let mut node = Node::default();
+            node[0] = 7;
+            node
+        };
+        let mut cache = CachedTree::new(&[leaf]).unwrap();
+        assert_eq!(cache.root(), leaf);
+
+        let mut updated = Node::default();
+        updated[0] = 9;
+        cache.update_leaf(1, updated).unwrap();
+        assert_eq!(cache.root(), updated);
+    }
+
+    // Recomputes a 4-leaf root from scratch, independent of `CachedTree`, as a
+    // ground truth to compare the cache's incremental result against.
+    fn calculate_merkle_root_from_leaves(leaves: &[Node]) -> Node {
+        let indices = vec![4, 5, 6, 7];
+        let (proof, _) = generate_multiproof(leaves, &indices).unwrap();
+        calculate_multi_merkle_root(leaves, &proof, &indices).unwrap()
+    }
+
+    #[test]
+    fn test_range_proof_round_trips_for_vector() {
+        let all_leaves = four_leaves();
+        let (range_leaves, proof) = generate_range_proof(&all_leaves, 1, 3).unwrap();
+        assert_eq!(range_leaves, all_leaves[1..3]);
+
+        let root = calculate_merkle_root_from_leaves(&all_leaves);
+        let result = verify_range_proof(root, 1, 3, &range_leaves, &proof, 4, None);
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_range_proof_round_trips_for_list_with_length_mix_in() {
+        let chunks = four_leaves();
+        let mut length_node = Node::default();
+        length_node[0] = 2; // pretend a 2-element list.
+
+        let (range_leaves, proof) = generate_range_proof(&chunks, 0, 2).unwrap();
+        let chunks_root = calculate_merkle_root_from_leaves(&chunks);
+        let mut hasher = Sha256::new();
+        hasher.update(chunks_root);
+        hasher.update(length_node);
+        let mut root = Node::default();
+        root.copy_from_slice(&hasher.finalize());
+
+        let result =
+            verify_range_proof(root, 0, 2, &range_leaves, &proof, 4, Some(length_node));
+        assert!(result.is_ok());
+
+        // Omitting the length mix-in must not verify against the same root.
+        let result = verify_range_proof(root, 0, 2, &range_leaves, &proof, 4, None);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_range_proof_rejects_tampered_range() {
+        let all_leaves = four_leaves();
+        let (_, proof) = generate_range_proof(&all_leaves, 1, 3).unwrap();
+        let root = calculate_merkle_root_from_leaves(&all_leaves);
+
+        let mut tampered_leaves = all_leaves[1..3].to_vec();
+        tampered_leaves[0][0] = 255;
+        let result = verify_range_proof(root, 1, 3, &tampered_leaves, &proof, 4, None);
+        assert!(result.is_err());
+    }
 }